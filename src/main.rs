@@ -1,13 +1,38 @@
 use anyhow::{Context, Result};
 use futures_util::StreamExt;
-use rusoto_core::Region;
-use rusoto_s3::S3;
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressStyle};
+use rand::Rng;
+use rusoto_core::{HttpClient, Region, RusotoError};
+use rusoto_credential::{DefaultCredentialsProvider, InstanceMetadataProvider, StaticProvider};
+use rusoto_s3::{GetObjectError, GetObjectOutput, HeadObjectError, S3};
+use rusoto_sts::WebIdentityProvider;
 use std::path::{Path, PathBuf};
-use std::{io::BufRead, str::FromStr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context as TaskContext, Poll};
+use std::time::Duration;
+use std::{
+    io::{BufRead, Write},
+    str::FromStr,
+};
 use structopt::StructOpt;
-use tracing::{error, info, instrument};
+use tokio::io::{AsyncRead, ReadBuf};
+use tracing::{error, info, instrument, warn};
 use tracing_subscriber::EnvFilter;
 
+/// Base delay used for the first retry; each subsequent retry doubles it.
+const BASE_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// Tuning knobs for a download, threaded from `Options` through
+/// `download_keys` -> `download_key` -> `try_download_once`.
+#[derive(Debug, Clone, Copy)]
+struct DownloadSettings {
+    request_timeout: Duration,
+    max_retries: u32,
+    resume: bool,
+    verify: bool,
+}
+
 /// Download files from S3 in parallel
 #[derive(StructOpt)]
 struct Options {
@@ -17,8 +42,19 @@ struct Options {
 
     /// A path to a newline-separated file of AWS S3 keys to download.
     /// The keys should be relative, like `a/path/to/a/file.jpg`
-    #[structopt(long, short)]
-    keys_path: PathBuf,
+    /// Mutually exclusive with `--prefix`.
+    #[structopt(long, short, conflicts_with = "prefix")]
+    keys_path: Option<PathBuf>,
+
+    /// Download every key under this prefix, discovered via a paginated
+    /// ListObjectsV2 call, instead of reading a keys file.
+    /// Mutually exclusive with `--keys-path`.
+    #[structopt(long, conflicts_with = "keys-path")]
+    prefix: Option<String>,
+
+    /// Restrict a `--prefix` listing to a single "directory" level, e.g. "/".
+    #[structopt(long, requires = "prefix")]
+    delimiter: Option<String>,
 
     /// Where the downloaded files should be written.
     #[structopt(long, short = "o")]
@@ -39,6 +75,51 @@ struct Options {
 
     #[structopt(long, short = "l", possible_values = &EventFormat::variants(), default_value = "full")]
     event_format: EventFormat,
+
+    /// How long, in seconds, to wait for a single S3 request (or a single
+    /// body-copy operation) before treating it as failed and retrying.
+    #[structopt(long, default_value = "30")]
+    request_timeout_secs: u64,
+
+    /// Maximum number of retries for a retryable error before giving up on a key.
+    #[structopt(long, default_value = "5")]
+    max_retries: u32,
+
+    /// Resume partial downloads using HTTP Range requests instead of
+    /// restarting them from byte zero. Correctness is guarded by comparing
+    /// the remote object's ETag against the one recorded when the partial
+    /// download began; a changed object is downloaded in full again.
+    #[structopt(long)]
+    resume: bool,
+
+    /// Verify each completed download's MD5 against its ETag (single-part
+    /// objects only). A mismatch is logged and the key is re-downloaded.
+    /// Downloads resumed with `--resume` are not verified.
+    #[structopt(long)]
+    verify: bool,
+
+    /// Show a live aggregate progress bar (keys and bytes completed), plus a
+    /// per-key byte bar for any key whose size is known up front.
+    #[structopt(long)]
+    progress: bool,
+
+    /// A custom S3-compatible endpoint URL, for MinIO, R2, Ceph, and the
+    /// like. When given, the client talks to a `Region::Custom` built from
+    /// this endpoint and `--region` (or "custom" if `--region` is unset).
+    #[structopt(long)]
+    endpoint: Option<String>,
+
+    /// Which credential provider to build the S3 client with.
+    #[structopt(long, possible_values = &CredentialsSource::variants(), default_value = "default")]
+    credentials: CredentialsSource,
+
+    /// Static access key ID, used with `--credentials static`.
+    #[structopt(long, env = "AWS_ACCESS_KEY_ID", hide_env_values = true)]
+    access_key_id: Option<String>,
+
+    /// Static secret access key, used with `--credentials static`.
+    #[structopt(long, env = "AWS_SECRET_ACCESS_KEY", hide_env_values = true)]
+    secret_access_key: Option<String>,
 }
 
 #[derive(Clone, Copy)]
@@ -49,11 +130,17 @@ enum OnExistingFile {
     Error,
     /// Download and overwrite the file
     Overwrite,
+    /// Do not download the file if a `head_object` call shows the remote size
+    /// equals the local file's size and the remote `ETag` equals the one
+    /// recorded the last time it was downloaded in this mode. Otherwise --
+    /// including when no `ETag` was recorded or the check fails -- re-download
+    /// and overwrite it.
+    SkipIfUnchanged,
 }
 
 impl OnExistingFile {
-    fn variants() -> [&'static str; 3] {
-        ["skip", "overwrite", "error"]
+    fn variants() -> [&'static str; 4] {
+        ["skip", "overwrite", "error", "skip-if-unchanged"]
     }
 }
 
@@ -65,6 +152,7 @@ impl FromStr for OnExistingFile {
             "skip" => Ok(OnExistingFile::Skip),
             "overwrite" => Ok(OnExistingFile::Overwrite),
             "error" => Ok(OnExistingFile::Error),
+            "skip-if-unchanged" => Ok(OnExistingFile::SkipIfUnchanged),
             _ => Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidInput,
                 format!(
@@ -106,22 +194,53 @@ impl FromStr for EventFormat {
     }
 }
 
-macro_rules! ok_or_err {
-    ($result:expr) => {
-        match $result {
-            Ok(value) => value,
-            Err(e) => {
-                error!(error = %e);
-                return;
-            }
+#[derive(Clone, Copy)]
+enum CredentialsSource {
+    /// Whatever the ambient rusoto provider chain finds (env vars, shared
+    /// config/credentials files, IAM role, etc).
+    Default,
+    /// A static access key and secret, from `--access-key-id` /
+    /// `--secret-access-key` or their `AWS_*` environment variables.
+    Static,
+    /// Web identity / STS-based credentials, e.g. for EKS IRSA or GitHub OIDC.
+    WebIdentity,
+    /// The EC2 instance metadata service.
+    InstanceMetadata,
+}
+
+impl CredentialsSource {
+    fn variants() -> [&'static str; 4] {
+        ["default", "static", "web-identity", "instance-metadata"]
+    }
+}
+
+impl FromStr for CredentialsSource {
+    type Err = std::io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(CredentialsSource::Default),
+            "static" => Ok(CredentialsSource::Static),
+            "web-identity" => Ok(CredentialsSource::WebIdentity),
+            "instance-metadata" => Ok(CredentialsSource::InstanceMetadata),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "credentials must be one of {:?}",
+                    CredentialsSource::variants()
+                ),
+            )),
         }
-    };
-    ($result:expr, $key:expr) => {
+    }
+}
+
+macro_rules! ok_or_err {
+    ($result:expr, $key:expr, $ret:expr) => {
         match $result {
             Ok(value) => value,
             Err(e) => {
                 error!(key = $key.as_str(), error = %e);
-                return;
+                return $ret;
             }
         }
     };
@@ -131,122 +250,861 @@ macro_rules! ok_or_err {
 async fn main() -> Result<()> {
     let options = Options::from_args();
 
-    configure_logging(&options);
+    let progress = options.progress.then(|| Arc::new(MultiProgress::new()));
 
-    let region = if let Some(region) = options.region {
-        region
-    } else {
-        rusoto_core::Region::default()
-    };
+    configure_logging(&options, progress.clone());
 
-    let client = rusoto_s3::S3Client::new(region);
+    let client = build_client(&options)?;
+
+    if options.keys_path.is_none() && options.prefix.is_none() {
+        anyhow::bail!("one of --keys-path or --prefix is required");
+    }
+
+    let settings = DownloadSettings {
+        request_timeout: Duration::from_secs(options.request_timeout_secs),
+        max_retries: options.max_retries,
+        resume: options.resume,
+        verify: options.verify,
+    };
 
     download_keys(
         client,
         options.bucket,
         options.keys_path,
+        options.prefix,
+        options.delimiter,
         options.out_path,
         options.on_existing_file,
         options.parallelism.unwrap_or_else(|| num_cpus::get() * 10),
+        settings,
+        progress,
     )
     .await?;
 
     Ok(())
 }
 
+/// Build the S3 client according to `--endpoint` and `--credentials`.
+fn build_client(options: &Options) -> Result<rusoto_s3::S3Client> {
+    let region = match &options.endpoint {
+        Some(endpoint) => Region::Custom {
+            name: options
+                .region
+                .as_ref()
+                .map(|region| region.name().to_string())
+                .unwrap_or_else(|| "custom".to_string()),
+            endpoint: endpoint.clone(),
+        },
+        None => options.region.clone().unwrap_or_default(),
+    };
+
+    let client = match options.credentials {
+        CredentialsSource::Default => rusoto_s3::S3Client::new_with(
+            HttpClient::new().context("failed to build HTTP client")?,
+            DefaultCredentialsProvider::new()
+                .context("failed to build default credentials provider")?,
+            region,
+        ),
+        CredentialsSource::Static => {
+            let access_key_id = options.access_key_id.clone().context(
+                "--access-key-id (or AWS_ACCESS_KEY_ID) is required for --credentials static",
+            )?;
+            let secret_access_key = options.secret_access_key.clone().context(
+                "--secret-access-key (or AWS_SECRET_ACCESS_KEY) is required for --credentials static",
+            )?;
+
+            rusoto_s3::S3Client::new_with(
+                HttpClient::new().context("failed to build HTTP client")?,
+                StaticProvider::new_minimal(access_key_id, secret_access_key),
+                region,
+            )
+        }
+        CredentialsSource::WebIdentity => rusoto_s3::S3Client::new_with(
+            HttpClient::new().context("failed to build HTTP client")?,
+            WebIdentityProvider::from_k8s_env(),
+            region,
+        ),
+        CredentialsSource::InstanceMetadata => rusoto_s3::S3Client::new_with(
+            HttpClient::new().context("failed to build HTTP client")?,
+            InstanceMetadataProvider::new(),
+            region,
+        ),
+    };
+
+    Ok(client)
+}
+
+/// Lazily list every key under `prefix`, paginating through `ListObjectsV2`
+/// as the returned stream is polled. With a `delimiter`, keys that contain it
+/// after `prefix` are rolled up by S3 into common prefixes and not yielded.
+fn paginate_prefix(
+    client: rusoto_s3::S3Client,
+    bucket: String,
+    prefix: String,
+    delimiter: Option<String>,
+) -> impl futures_util::Stream<Item = Result<String>> {
+    enum PageState {
+        More(Option<String>),
+        Done,
+    }
+
+    futures_util::stream::unfold(PageState::More(None), move |state| {
+        let client = client.clone();
+        let bucket = bucket.clone();
+        let prefix = prefix.clone();
+        let delimiter = delimiter.clone();
+
+        async move {
+            let continuation_token = match state {
+                PageState::More(token) => token,
+                PageState::Done => return None,
+            };
+
+            let req = rusoto_s3::ListObjectsV2Request {
+                bucket,
+                prefix: Some(prefix),
+                continuation_token,
+                delimiter,
+                ..Default::default()
+            };
+
+            let resp = match client.list_objects_v2(req).await {
+                Ok(resp) => resp,
+                Err(e) => {
+                    let err = futures_util::stream::iter(vec![Err(anyhow::Error::from(e))]);
+                    return Some((err, PageState::Done));
+                }
+            };
+
+            let keys = resp
+                .contents
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|object| object.key)
+                .map(Ok)
+                .collect::<Vec<_>>();
+
+            let next_state = match (resp.is_truncated, resp.next_continuation_token) {
+                (Some(true), Some(token)) => PageState::More(Some(token)),
+                _ => PageState::Done,
+            };
+
+            Some((futures_util::stream::iter(keys), next_state))
+        }
+    })
+    .flatten()
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn download_keys(
     client: rusoto_s3::S3Client,
     bucket: String,
-    keys_path: PathBuf,
+    keys_path: Option<PathBuf>,
+    prefix: Option<String>,
+    delimiter: Option<String>,
     out_path: PathBuf,
     on_existing_file: OnExistingFile,
     parallelism: usize,
+    settings: DownloadSettings,
+    progress: Option<Arc<MultiProgress>>,
 ) -> Result<()> {
-    let keys_file = std::fs::File::open(&keys_path)?;
-    let keys_buf = std::io::BufReader::new(keys_file);
-    let keys_lines = futures_util::stream::iter(keys_buf.lines());
-
-    let stream = keys_lines.map(|line| {
-        let key = line.unwrap();
-        download_key(
-            client.clone(),
-            bucket.clone(),
-            key,
-            out_path.clone(),
-            on_existing_file,
-        )
+    let mut total_keys = None;
+
+    // Carried through to each `download_key` call so `--prefix` downloads can
+    // preserve the key's subdirectory structure under `out_path` instead of
+    // flattening every key to its basename.
+    let key_prefix = prefix.clone();
+
+    let keys_stream: std::pin::Pin<Box<dyn futures_util::Stream<Item = Result<String>> + Send>> =
+        if let Some(keys_path) = keys_path {
+            let keys_file = std::fs::File::open(&keys_path)?;
+            let keys_buf = std::io::BufReader::new(keys_file);
+            let lines = keys_buf
+                .lines()
+                .map(|line| line.map_err(anyhow::Error::from))
+                .collect::<Vec<_>>();
+            total_keys = Some(lines.len() as u64);
+            Box::pin(futures_util::stream::iter(lines))
+        } else if let Some(prefix) = prefix {
+            Box::pin(paginate_prefix(
+                client.clone(),
+                bucket.clone(),
+                prefix,
+                delimiter,
+            ))
+        } else {
+            anyhow::bail!("one of --keys-path or --prefix is required");
+        };
+
+    let aggregate_bar = progress.as_ref().map(|multi| {
+        let bar = match total_keys {
+            Some(total) => multi.add(ProgressBar::new(total)),
+            None => multi.add(ProgressBar::new_spinner()),
+        };
+
+        let template = match total_keys {
+            Some(_) => "{spinner} [{elapsed_precise}] {pos}/{len} keys ({msg})",
+            None => "{spinner} [{elapsed_precise}] {pos} keys ({msg})",
+        };
+
+        bar.set_style(
+            ProgressStyle::default_bar()
+                .template(template)
+                .expect("static progress template is valid"),
+        );
+        bar.set_message(format!("{}", HumanBytes(0)));
+        bar
+    });
+
+    let stream = keys_stream.filter_map(|key_result| {
+        let client = client.clone();
+        let bucket = bucket.clone();
+        let out_path = out_path.clone();
+        let key_prefix = key_prefix.clone();
+        let progress = progress.clone();
+
+        Box::pin(async move {
+            match key_result {
+                Ok(key) => Some(download_key(
+                    client,
+                    bucket,
+                    key,
+                    out_path,
+                    key_prefix,
+                    on_existing_file,
+                    settings,
+                    progress,
+                )),
+                Err(e) => {
+                    error!(error = %e, "failed to list an object key, skipping");
+                    None
+                }
+            }
+        })
     });
 
     let mut buffered = stream.buffer_unordered(parallelism);
 
+    let mut total_bytes: u64 = 0;
+
     // we do it this way because Rust does not have async for-loops yet
-    while buffered.next().await.is_some() {}
+    while let Some(bytes) = buffered.next().await {
+        if let Some(bar) = &aggregate_bar {
+            total_bytes += bytes;
+            bar.inc(1);
+            bar.set_message(format!("{}", HumanBytes(total_bytes)));
+        }
+    }
+
+    if let Some(bar) = &aggregate_bar {
+        bar.finish();
+    }
 
     Ok(())
 }
 
-#[instrument(skip(client, bucket, out_path, on_existing_file))]
+/// The outcome of a single download attempt, distinguishing errors worth
+/// retrying (timeouts, 5xx, throttling, connection resets) from errors that
+/// will never succeed no matter how many times we try (404, 403, ...).
+enum DownloadError {
+    Retryable(anyhow::Error),
+    NotRetryable(anyhow::Error),
+}
+
+/// Whether a `GetObjectError` is worth retrying.
+fn is_retryable_get_object_error(err: &RusotoError<GetObjectError>) -> bool {
+    match err {
+        RusotoError::HttpDispatch(_) => true,
+        RusotoError::Unknown(resp) => {
+            matches!(resp.status.as_u16(), 429 | 500..=599)
+        }
+        _ => false,
+    }
+}
+
+/// Whether a `HeadObjectError` is worth retrying.
+fn is_retryable_head_object_error(err: &RusotoError<HeadObjectError>) -> bool {
+    match err {
+        RusotoError::HttpDispatch(_) => true,
+        RusotoError::Unknown(resp) => {
+            matches!(resp.status.as_u16(), 429 | 500..=599)
+        }
+        _ => false,
+    }
+}
+
+/// Downloads `key`, returning the number of bytes written on success, or 0
+/// if the key could not be downloaded at all.
+#[instrument(skip(
+    client,
+    bucket,
+    out_path,
+    key_prefix,
+    on_existing_file,
+    settings,
+    progress
+))]
 async fn download_key(
     client: rusoto_s3::S3Client,
     bucket: String,
     key: String,
     out_path: PathBuf,
+    key_prefix: Option<String>,
     on_existing_file: OnExistingFile,
-) {
+    settings: DownloadSettings,
+    progress: Option<Arc<MultiProgress>>,
+) -> u64 {
     let mut out_path = out_path;
 
-    let req = rusoto_s3::GetObjectRequest {
-        bucket,
-        key: key.clone(),
-        ..Default::default()
-    };
-
     info!(key = key.as_str(), status = "started");
 
-    let filename = Path::new(&key).file_name().unwrap();
+    // `--prefix` listings are recursive and routinely contain same-named
+    // keys under different "subdirectories" (`_SUCCESS`, per-partition
+    // manifests, ...), so flattening to the basename would let concurrent
+    // downloads race on the same local path. Preserve the key's path
+    // relative to the listed prefix instead (the whole key, when the prefix
+    // ends partway through a path component); plain `--keys-path` downloads
+    // keep flattening to the basename, as before.
+    match &key_prefix {
+        Some(prefix) => {
+            let relative = Path::new(&key)
+                .strip_prefix(prefix)
+                .unwrap_or_else(|_| Path::new(&key));
 
-    out_path.push(filename);
+            out_path.push(relative);
+        }
+        None => {
+            let filename = Path::new(&key).file_name().unwrap();
+            out_path.push(filename);
+        }
+    }
+
+    if let Some(parent) = out_path.parent() {
+        ok_or_err!(
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("Could not create directory: {:?}", parent)),
+            key,
+            0
+        );
+    }
 
     match &on_existing_file {
         OnExistingFile::Skip => {
-            if Path::new(&out_path).exists() {
-                return;
+            let has_resumable_partial = settings.resume && resume_marker_path(&out_path).exists();
+
+            if Path::new(&out_path).exists() && !has_resumable_partial {
+                return 0;
             }
         }
         OnExistingFile::Error => {
             if Path::new(&out_path).exists() {
-                ok_or_err!(Err(anyhow::anyhow!("{:?} already exists", key)), key);
+                ok_or_err!(Err(anyhow::anyhow!("{:?} already exists", key)), key, 0);
             }
         }
         OnExistingFile::Overwrite => (),
+        OnExistingFile::SkipIfUnchanged => {
+            if Path::new(&out_path).exists() {
+                match is_unchanged(&client, &bucket, &key, &out_path, settings.request_timeout)
+                    .await
+                {
+                    Ok(true) => {
+                        info!(key = key.as_str(), status = "unchanged, skipping");
+                        return 0;
+                    }
+                    Ok(false) => (),
+                    Err(e) => {
+                        warn!(key = key.as_str(), error = %e, "freshness check failed, re-downloading");
+                    }
+                }
+            }
+        }
+    }
+
+    // a generous ceiling on total time spent retrying, on top of max_retries
+    let deadline =
+        tokio::time::Instant::now() + settings.request_timeout * (settings.max_retries + 1);
+    let mut attempt = 0;
+
+    loop {
+        match try_download_once(
+            &client,
+            bucket.clone(),
+            key.clone(),
+            &out_path,
+            on_existing_file,
+            settings,
+            progress.as_deref(),
+        )
+        .await
+        {
+            Ok(bytes) => {
+                info!(key = key.as_str(), status = "finished");
+                return bytes;
+            }
+            Err(DownloadError::NotRetryable(e)) => {
+                error!(key = key.as_str(), error = %e);
+                return 0;
+            }
+            Err(DownloadError::Retryable(e)) => {
+                if attempt >= settings.max_retries || tokio::time::Instant::now() >= deadline {
+                    error!(key = key.as_str(), attempt, error = %e, "giving up after retries");
+                    return 0;
+                }
+
+                // Cap the exponent well below where `2u32.pow` would overflow;
+                // backoff is already measured in hours by this point anyway.
+                let backoff = BASE_RETRY_DELAY * 2u32.pow(attempt.min(20));
+                let jitter = rand::thread_rng().gen_range(Duration::ZERO..=backoff);
+
+                warn!(key = key.as_str(), attempt, error = %e, delay_ms = jitter.as_millis() as u64, "retrying download");
+
+                tokio::time::sleep(jitter).await;
+                attempt += 1;
+            }
+        }
     }
+}
+
+/// Where we record the ETag of the object a partial download belongs to, for
+/// comparison on a later `--resume` attempt.
+fn resume_marker_path(out_path: &Path) -> PathBuf {
+    let mut marker = out_path.as_os_str().to_owned();
+    marker.push(".s3dl-resume");
+    PathBuf::from(marker)
+}
+
+/// The subset of `HeadObjectOutput` we need to decide whether a partial
+/// download can be resumed.
+struct RemoteObjectInfo {
+    etag: Option<String>,
+    content_length: Option<i64>,
+}
+
+async fn head_object_info(
+    client: &rusoto_s3::S3Client,
+    bucket: &str,
+    key: &str,
+) -> Result<RemoteObjectInfo, RusotoError<HeadObjectError>> {
+    let resp = client
+        .head_object(rusoto_s3::HeadObjectRequest {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(RemoteObjectInfo {
+        etag: resp.e_tag,
+        content_length: resp.content_length,
+    })
+}
+
+/// Where we record the ETag of a *completed* download, so a later
+/// `OnExistingFile::SkipIfUnchanged` run can tell whether the remote object
+/// has changed without downloading it again.
+fn freshness_marker_path(out_path: &Path) -> PathBuf {
+    let mut marker = out_path.as_os_str().to_owned();
+    marker.push(".s3dl-etag");
+    PathBuf::from(marker)
+}
+
+/// Whether the local file at `out_path` is still fresh, judged by comparing
+/// its size and recorded ETag against a `head_object` call.
+async fn is_unchanged(
+    client: &rusoto_s3::S3Client,
+    bucket: &str,
+    key: &str,
+    out_path: &Path,
+    request_timeout: Duration,
+) -> Result<bool> {
+    let Some(saved_etag) = tokio::fs::read_to_string(freshness_marker_path(out_path))
+        .await
+        .ok()
+    else {
+        return Ok(false);
+    };
+
+    let local_len = tokio::fs::metadata(out_path).await?.len();
+
+    let remote = tokio::time::timeout(request_timeout, head_object_info(client, bucket, key))
+        .await
+        .context("head_object timed out")??;
+
+    Ok(remote.etag.as_deref() == Some(saved_etag.as_str())
+        && remote.content_length.map(|len| len as u64) == Some(local_len))
+}
+
+/// Decide whether an existing local file can be resumed, and prepare the
+/// file handle and `Range` header accordingly.
+async fn prepare_download_file(
+    client: &rusoto_s3::S3Client,
+    bucket: &str,
+    key: &str,
+    out_path: &Path,
+    request_timeout: Duration,
+    resume: bool,
+) -> Result<(tokio::fs::File, Option<String>), DownloadError> {
+    let marker_path = resume_marker_path(out_path);
+
+    if resume {
+        let local_len = tokio::fs::metadata(out_path)
+            .await
+            .map(|m| m.len())
+            .unwrap_or(0);
+
+        if local_len == 0 {
+            let file = tokio::fs::File::create(out_path)
+                .await
+                .with_context(|| format!("Could not create local file: {:?}", out_path))
+                .map_err(DownloadError::NotRetryable)?;
+
+            return Ok((file, None));
+        }
+
+        let remote = tokio::time::timeout(request_timeout, head_object_info(client, bucket, key))
+            .await
+            .map_err(|_elapsed| DownloadError::Retryable(anyhow::anyhow!("head_object timed out")))?
+            .map_err(|e| {
+                if is_retryable_head_object_error(&e) {
+                    DownloadError::Retryable(e.into())
+                } else {
+                    DownloadError::NotRetryable(e.into())
+                }
+            })?;
+
+        let saved_etag = tokio::fs::read_to_string(&marker_path).await.ok();
+
+        let can_resume = saved_etag.is_some()
+            && saved_etag == remote.etag
+            && remote
+                .content_length
+                .map(|remote_len| local_len < remote_len as u64)
+                .unwrap_or(false);
+
+        if can_resume {
+            info!(key = key, local_len, "resuming partial download");
 
-    let mut file = ok_or_err!(
-        tokio::fs::File::create(&out_path)
+            let file = tokio::fs::OpenOptions::new()
+                .append(true)
+                .open(out_path)
+                .await
+                .with_context(|| format!("Could not open local file for append: {:?}", out_path))
+                .map_err(DownloadError::NotRetryable)?;
+
+            return Ok((file, Some(format!("bytes={}-", local_len))));
+        }
+
+        let file = tokio::fs::File::create(out_path)
             .await
-            .with_context(|| format!("Could not create local file: {:?}", out_path)),
-        key
+            .with_context(|| format!("Could not create local file: {:?}", out_path))
+            .map_err(DownloadError::NotRetryable)?;
+
+        return Ok((file, None));
+    }
+
+    let file = tokio::fs::File::create(out_path)
+        .await
+        .with_context(|| format!("Could not create local file: {:?}", out_path))
+        .map_err(DownloadError::NotRetryable)?;
+
+    Ok((file, None))
+}
+
+/// What a completed download should be checked against, derived from the
+/// object's `ETag`. `rusoto_s3` has no support for S3's `x-amz-checksum-*`
+/// response headers, so a multipart object (whose `ETag` is not a plain MD5)
+/// can't be verified here and `--verify` is skipped for it.
+enum VerifyMode {
+    /// Single-part object: `ETag` is a plain MD5 hex digest.
+    Md5(String),
+}
+
+/// Multipart ETags look like `"<hash>-<partcount>"`; a plain MD5 ETag is 32
+/// hex characters with no dash.
+fn classify_verify_mode(key: &str, resp: &GetObjectOutput) -> Option<VerifyMode> {
+    let etag = resp.e_tag.as_deref()?.trim_matches('"');
+
+    if !etag.contains('-') && etag.len() == 32 && etag.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Some(VerifyMode::Md5(etag.to_lowercase()));
+    }
+
+    warn!(
+        key,
+        etag, "multipart object's ETag isn't a plain MD5; skipping --verify for this key"
     );
+    None
+}
+
+enum Hasher {
+    Md5(md5::Context),
+}
+
+impl Hasher {
+    fn new(mode: &VerifyMode) -> Self {
+        match mode {
+            VerifyMode::Md5(_) => Hasher::Md5(md5::Context::new()),
+        }
+    }
+
+    fn consume(&mut self, bytes: &[u8]) {
+        match self {
+            Hasher::Md5(ctx) => ctx.consume(bytes),
+        }
+    }
+
+    /// Finalize the hash and compare it against what `mode` expects.
+    fn matches(self, mode: &VerifyMode) -> bool {
+        match (self, mode) {
+            (Hasher::Md5(ctx), VerifyMode::Md5(expected)) => {
+                format!("{:x}", ctx.compute()) == *expected
+            }
+        }
+    }
+}
+
+/// Tees every byte read from `inner` into a per-key progress bar.
+struct ProgressReader<R> {
+    inner: R,
+    bar: ProgressBar,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ProgressReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if result.is_ready() {
+            this.bar.inc((buf.filled().len() - before) as u64);
+        }
+        result
+    }
+}
+
+/// Tees every byte written to `inner` through a `Hasher`, so a download's
+/// integrity can be verified without re-reading the file back off disk.
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Hasher,
+}
+
+impl<W: tokio::io::AsyncWrite + Unpin> tokio::io::AsyncWrite for HashingWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        match Pin::new(&mut this.inner).poll_write(cx, buf) {
+            Poll::Ready(Ok(n)) => {
+                this.hasher.consume(&buf[..n]);
+                Poll::Ready(Ok(n))
+            }
+            other => other,
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// Make a single attempt at downloading `key` to `out_path`, returning the
+/// number of bytes copied. Absent `--resume`, any previous partial contents
+/// are truncated first. Each request (the `--resume` `head_object` check and
+/// the `get_object` call) and the body copy are separately bounded by
+/// `settings.request_timeout`, so one attempt can take several times that.
+async fn try_download_once(
+    client: &rusoto_s3::S3Client,
+    bucket: String,
+    key: String,
+    out_path: &Path,
+    on_existing_file: OnExistingFile,
+    settings: DownloadSettings,
+    progress: Option<&MultiProgress>,
+) -> Result<u64, DownloadError> {
+    let request_timeout = settings.request_timeout;
+
+    let (mut file, range) = prepare_download_file(
+        client,
+        &bucket,
+        &key,
+        out_path,
+        request_timeout,
+        settings.resume,
+    )
+    .await?;
+
+    // A resumed, partial-range body only covers the tail of the object, so
+    // there's nothing sensible to hash it against; only verify full downloads.
+    let verify_this_attempt = settings.verify && range.is_none();
+
+    let req = rusoto_s3::GetObjectRequest {
+        bucket,
+        key: key.clone(),
+        range,
+        ..Default::default()
+    };
+
+    let resp = match tokio::time::timeout(request_timeout, client.get_object(req)).await {
+        Ok(Ok(resp)) => resp,
+        Ok(Err(e)) => {
+            return if is_retryable_get_object_error(&e) {
+                Err(DownloadError::Retryable(e.into()))
+            } else {
+                Err(DownloadError::NotRetryable(e.into()))
+            };
+        }
+        Err(_elapsed) => {
+            return Err(DownloadError::Retryable(anyhow::anyhow!(
+                "get_object request timed out after {:?}",
+                request_timeout
+            )));
+        }
+    };
 
-    let resp = ok_or_err!(client.get_object(req).await, key);
+    let remote_etag = resp.e_tag.clone();
 
-    let body = ok_or_err!(resp.body.ok_or("response body was empty"), key);
+    // A fresh (non-resumed) download under --resume is itself a partial file
+    // until the copy below finishes; record the marker now so a process that
+    // dies mid-copy still leaves behind an ETag a later --resume run can use.
+    if settings.resume && range.is_none() {
+        if let Some(etag) = &remote_etag {
+            let _ = tokio::fs::write(resume_marker_path(out_path), etag).await;
+        }
+    }
 
-    let mut async_body = body.into_async_read();
+    let verify_mode = verify_this_attempt
+        .then(|| classify_verify_mode(&key, &resp))
+        .flatten();
 
-    ok_or_err!(tokio::io::copy(&mut async_body, &mut file).await, key);
+    let per_key_bar = progress.zip(resp.content_length).map(|(multi, len)| {
+        let bar = multi.add(ProgressBar::new(len.max(0) as u64));
+        bar.set_style(
+            ProgressStyle::default_bar()
+                .template("{bar:30} {bytes}/{total_bytes} {msg}")
+                .expect("static progress template is valid"),
+        );
+        bar.set_message(key.clone());
+        bar
+    });
 
-    info!(key = key.as_str(), status = "finished");
+    let body = resp
+        .body
+        .ok_or_else(|| anyhow::anyhow!("response body was empty"))
+        .map_err(DownloadError::NotRetryable)?;
+
+    let async_body = body.into_async_read();
+    let mut async_body: Pin<Box<dyn AsyncRead + Send>> = match &per_key_bar {
+        Some(bar) => Box::pin(ProgressReader {
+            inner: async_body,
+            bar: bar.clone(),
+        }),
+        None => Box::pin(async_body),
+    };
+
+    let copy_result = if let Some(mode) = &verify_mode {
+        let mut writer = HashingWriter {
+            inner: &mut file,
+            hasher: Hasher::new(mode),
+        };
+
+        tokio::time::timeout(
+            request_timeout,
+            tokio::io::copy(&mut async_body, &mut writer),
+        )
+        .await
+        .map(|r| r.map(|n| (n, Some(writer.hasher))))
+    } else {
+        tokio::time::timeout(request_timeout, tokio::io::copy(&mut async_body, &mut file))
+            .await
+            .map(|r| r.map(|n| (n, None)))
+    };
+
+    if let Some(bar) = &per_key_bar {
+        bar.finish_and_clear();
+    }
+
+    match copy_result {
+        Ok(Ok((bytes, hasher))) => {
+            if let (Some(mode), Some(hasher)) = (&verify_mode, hasher) {
+                if !hasher.matches(mode) {
+                    error!(
+                        key = key.as_str(),
+                        "checksum mismatch, deleting and retrying"
+                    );
+                    let _ = tokio::fs::remove_file(out_path).await;
+                    return Err(DownloadError::Retryable(anyhow::anyhow!(
+                        "downloaded file failed --verify checksum comparison"
+                    )));
+                }
+            }
+
+            if settings.resume {
+                let _ = tokio::fs::remove_file(resume_marker_path(out_path)).await;
+            }
+
+            if matches!(on_existing_file, OnExistingFile::SkipIfUnchanged) {
+                if let Some(etag) = &remote_etag {
+                    let _ = tokio::fs::write(freshness_marker_path(out_path), etag).await;
+                }
+            }
+
+            Ok(bytes)
+        }
+        Ok(Err(e)) => Err(DownloadError::Retryable(e.into())),
+        Err(_elapsed) => Err(DownloadError::Retryable(anyhow::anyhow!(
+            "body copy timed out after {:?}",
+            request_timeout
+        ))),
+    }
+}
+
+/// A `tracing` writer that suspends the `--progress` bars for the duration
+/// of each write. Without `--progress` it writes straight to stderr.
+#[derive(Clone)]
+struct ProgressAwareWriter {
+    multi: Option<Arc<MultiProgress>>,
+}
+
+impl Write for ProgressAwareWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match &self.multi {
+            Some(multi) => multi.suspend(|| std::io::stderr().write_all(buf))?,
+            None => std::io::stderr().write_all(buf)?,
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        std::io::stderr().flush()
+    }
+}
+
+impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for ProgressAwareWriter {
+    type Writer = ProgressAwareWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
 }
 
 // this function is stupid-long due to the way tracing does formatting types
 // https://github.com/tokio-rs/tracing/issues/575
-fn configure_logging(options: &Options) {
+fn configure_logging(options: &Options, progress: Option<Arc<MultiProgress>>) {
     let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
 
     let tracing_builder = tracing_subscriber::FmtSubscriber::builder()
         .with_env_filter(filter)
-        .with_writer(std::io::stderr);
+        .with_writer(ProgressAwareWriter { multi: progress });
 
     match options.event_format {
         EventFormat::Full => {